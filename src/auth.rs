@@ -0,0 +1,179 @@
+use crate::retry::{self, RetryPolicy};
+use mirror_error::MirrorError;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+
+// credentials presented to the token realm when the registry
+// requires authenticated pulls (private repositories)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+// a parsed `WWW-Authenticate: Bearer ...` challenge
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Challenge {
+    pub realm: String,
+    pub service: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+// parse a `WWW-Authenticate` header value, e.g.
+// `Bearer realm="https://auth.example.com/token",service="registry.example.com",scope="repository:foo:pull"`
+pub(crate) fn parse_challenge(header: &str) -> Result<Challenge, MirrorError> {
+    let header = header.trim();
+    let rest = header.strip_prefix("Bearer ").ok_or_else(|| {
+        MirrorError::new(&format!(
+            "[parse_challenge] unsupported authentication scheme: {header}"
+        ))
+    })?;
+
+    let mut realm = None;
+    let mut service = None;
+    let mut scope = None;
+    for part in rest.split(',') {
+        if let Some((key, value)) = part.trim().split_once('=') {
+            let value = value.trim_matches('"');
+            match key {
+                "realm" => realm = Some(value.to_string()),
+                "service" => service = Some(value.to_string()),
+                "scope" => scope = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Challenge {
+        realm: realm
+            .ok_or_else(|| MirrorError::new("[parse_challenge] challenge is missing a realm"))?,
+        service,
+        scope,
+    })
+}
+
+// exchange a challenge (plus optional credentials) for a bearer token;
+// transparently retries on 429 / 5xx per `retry_policy`, since token realms
+// (e.g. Docker Hub's) commonly rate-limit issuance independently of the
+// registry itself
+pub(crate) async fn fetch_token(
+    client: &Client,
+    challenge: &Challenge,
+    credentials: Option<&Credentials>,
+    retry_policy: &RetryPolicy,
+) -> Result<String, MirrorError> {
+    let mut query = Vec::new();
+    if let Some(service) = &challenge.service {
+        query.push(("service", service.as_str()));
+    }
+    if let Some(scope) = &challenge.scope {
+        query.push(("scope", scope.as_str()));
+    }
+
+    let mut attempt = 0;
+    let res = loop {
+        let mut req = client.get(&challenge.realm);
+        if !query.is_empty() {
+            req = req.query(&query);
+        }
+        if let Some(creds) = credentials {
+            req = req.basic_auth(&creds.username, Some(&creds.password));
+        }
+
+        let res = req
+            .send()
+            .await
+            .map_err(|e| MirrorError::new(&format!("[fetch_token] {e}")))?;
+        let status = res.status();
+        if !retry::should_retry(retry_policy, status, attempt) {
+            break res;
+        }
+        tokio::time::sleep(retry::wait_duration(retry_policy, &res, attempt)).await;
+        attempt += 1;
+    };
+
+    if res.status() != StatusCode::OK {
+        return Err(MirrorError::new(&format!(
+            "[fetch_token] token endpoint returned {}",
+            res.status()
+        )));
+    }
+
+    let body: TokenResponse = res.json().await.map_err(|e| {
+        MirrorError::new(&format!(
+            "[fetch_token] could not parse token response {e}"
+        ))
+    })?;
+
+    body.token
+        .or(body.access_token)
+        .ok_or_else(|| MirrorError::new("[fetch_token] response did not contain a token"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn fetch_token_retries_after_429_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/token")
+            .with_status(429)
+            .with_header("Retry-After", "0")
+            .expect(1)
+            .create();
+        server
+            .mock("GET", "/token")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(r#"{ "token": "abc123" }"#)
+            .create();
+
+        let client = Client::new();
+        let challenge = Challenge {
+            realm: format!("{url}/token"),
+            service: None,
+            scope: None,
+        };
+
+        let res = aw!(fetch_token(&client, &challenge, None, &RetryPolicy::default()));
+        assert_eq!(res.unwrap(), "abc123");
+    }
+
+    #[test]
+    fn parse_challenge_pass() {
+        let header = r#"Bearer realm="https://auth.example.com/token",service="registry.example.com",scope="repository:foo:pull""#;
+        let challenge = parse_challenge(header).unwrap();
+        assert_eq!(challenge.realm, "https://auth.example.com/token");
+        assert_eq!(challenge.service.as_deref(), Some("registry.example.com"));
+        assert_eq!(challenge.scope.as_deref(), Some("repository:foo:pull"));
+    }
+
+    #[test]
+    fn parse_challenge_without_scope_pass() {
+        let header = r#"Bearer realm="https://auth.example.com/token",service="registry.example.com""#;
+        let challenge = parse_challenge(header).unwrap();
+        assert_eq!(challenge.scope, None);
+    }
+
+    #[test]
+    fn parse_challenge_wrong_scheme_fail() {
+        let res = parse_challenge(r#"Basic realm="registry""#);
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use mirror_error::MirrorError;
+use sha2::{Digest, Sha256};
+
+// compute the `sha256:<hex>` digest of a raw response body, in the same
+// format the registry reports via `docker-content-digest`
+pub(crate) fn compute(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+// extract the digest pinned in a `.../manifests/sha256:...` request url, if any
+pub(crate) fn pinned(url: &str) -> Option<String> {
+    let (_, tail) = url.rsplit_once("/manifests/")?;
+    let tail = tail.split(['?', '&']).next().unwrap_or(tail);
+    tail.starts_with("sha256:").then(|| tail.to_string())
+}
+
+pub(crate) fn verify(expected: &str, actual: &str) -> Result<(), MirrorError> {
+    if expected != actual {
+        return Err(MirrorError::new(&format!(
+            "[verify] digest mismatch: expected {expected}, actual {actual}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_pass() {
+        assert_eq!(
+            compute(b""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn pinned_pass() {
+        let url = "https://registry.example.com/v2/foo/manifests/sha256:abc123";
+        assert_eq!(pinned(url), Some("sha256:abc123".to_string()));
+    }
+
+    #[test]
+    fn pinned_query_string_pass() {
+        let url = "https://registry.example.com/v2/foo/manifests/sha256:abc123?ns=docker.io";
+        assert_eq!(pinned(url), Some("sha256:abc123".to_string()));
+    }
+
+    #[test]
+    fn pinned_absent_pass() {
+        let url = "https://registry.example.com/v2/foo/manifests/latest";
+        assert_eq!(pinned(url), None);
+    }
+
+    #[test]
+    fn verify_mismatch_fail() {
+        assert!(verify("sha256:a", "sha256:b").is_err());
+    }
+}
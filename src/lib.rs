@@ -1,10 +1,137 @@
+mod auth;
+mod digest;
+mod manifest;
+mod retry;
+
 use async_trait::async_trait;
+use futures_util::StreamExt;
 use mirror_error::MirrorError;
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
-use reqwest::{Client, StatusCode};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT, WWW_AUTHENTICATE,
+};
+use reqwest::{Client, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+pub use auth::Credentials;
+pub use manifest::{ManifestDescriptor, ManifestList, Platform};
+pub use retry::RetryPolicy;
+
+#[derive(Debug, Clone, Default)]
+pub struct ImplQueryImageInterface {
+    // bearer tokens obtained via get_details_authenticated, keyed by scope
+    token_cache: Arc<Mutex<HashMap<String, String>>>,
+    retry_policy: RetryPolicy,
+}
+
+impl ImplQueryImageInterface {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-#[derive(Debug, Clone)]
-pub struct ImplQueryImageInterface {}
+    pub fn with_retry_policy(retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..Self::default()
+        }
+    }
+
+    fn build_headers(token: &str) -> HeaderMap {
+        let mut header_map: HeaderMap = HeaderMap::new();
+        header_map.insert(USER_AGENT, HeaderValue::from_static("image-mirror"));
+        header_map.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        header_map.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json"),
+        );
+        header_map.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        header_map
+    }
+
+    // issue a GET, transparently retrying on 429 / 5xx per self.retry_policy;
+    // returns whatever response the last attempt produced (retry exhaustion
+    // and non-retryable statuses are left for the caller to turn into errors)
+    async fn get_with_retry(
+        &self,
+        client: &Client,
+        url: &str,
+        headers: HeaderMap,
+    ) -> Result<Response, MirrorError> {
+        let mut attempt = 0;
+        loop {
+            let res = client
+                .get(url)
+                .headers(headers.clone())
+                .send()
+                .await
+                .map_err(|e| MirrorError::new(&format!("[get_with_retry] {e}")))?;
+            let status = res.status();
+            if !retry::should_retry(&self.retry_policy, status, attempt) {
+                return Ok(res);
+            }
+            tokio::time::sleep(retry::wait_duration(&self.retry_policy, &res, attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn parse_ok_response(res: Response, e_tag: bool) -> Result<ResponseData, MirrorError> {
+        let headers = res.headers().clone();
+        if e_tag {
+            let e_tag = headers
+                .get("docker-content-digest")
+                .and_then(|h| h.to_str().ok())
+                .ok_or_else(|| {
+                    MirrorError::new("[parse_ok_response] missing docker-content-digest header")
+                })?;
+            Ok(ResponseData {
+                data: e_tag.to_string(),
+                link: "".to_string(),
+            })
+        } else {
+            let link_info = headers
+                .get("link")
+                .map(|l| {
+                    l.to_str()
+                        .unwrap()
+                        .replace(['<', '>'], "")
+                        .replace("; rel=\"next\"", "")
+                })
+                .unwrap_or_default();
+            let body = res.text().await.map_err(|e| {
+                MirrorError::new(&format!(
+                    "[get_details] could not read body contents {}",
+                    e.to_string().to_lowercase()
+                ))
+            })?;
+            Ok(ResponseData {
+                data: body,
+                link: link_info,
+            })
+        }
+    }
+}
+
+// merge the `repositories` (catalog) or `tags` (tag list) array of `page`
+// into the same array on `acc`, leaving every other field untouched
+fn merge_pages(acc: &mut serde_json::Value, page: &serde_json::Value) {
+    for key in ["repositories", "tags"] {
+        let Some(page_items) = page.get(key).and_then(|v| v.as_array()) else {
+            continue;
+        };
+        match acc.get_mut(key).and_then(|v| v.as_array_mut()) {
+            Some(acc_items) => acc_items.extend(page_items.clone()),
+            None => {
+                if let Some(obj) = acc.as_object_mut() {
+                    obj.insert(key.to_string(), serde_json::Value::Array(page_items.clone()));
+                }
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ResponseData {
@@ -25,6 +152,51 @@ pub trait QueryImageInterface {
         token: String,
         e_tag: bool,
     ) -> Result<ResponseData, MirrorError>;
+
+    // same as get_details, but when the registry responds with a
+    // `401 Unauthorized` and a `WWW-Authenticate: Bearer ...` challenge,
+    // transparently fetches a token from the challenge realm (optionally
+    // authenticating with credentials) and retries the request
+    async fn get_details_authenticated(
+        &self,
+        url: String,
+        credentials: Option<Credentials>,
+        e_tag: bool,
+    ) -> Result<ResponseData, MirrorError>;
+
+    // walks `link rel="next"` pagination to completion, merging the
+    // `repositories` (catalog) or `tags` (tag list) arrays of every page
+    // into a single response
+    async fn get_all(&self, url: String, token: String) -> Result<ResponseData, MirrorError>;
+
+    // like get_details, but verifies the downloaded body against the
+    // `docker-content-digest` header (and, for a digest-pinned url, against
+    // the pinned digest too) before returning it
+    async fn get_verified(&self, url: String, token: String) -> Result<ResponseData, MirrorError>;
+
+    // fetches a docker manifest-list / OCI image index at `url` and returns
+    // the digest of the child manifest matching (os, arch, variant); when
+    // `variant` is None, the first child matching os and architecture is
+    // used, while an explicit variant with no exact match is an error
+    async fn resolve_platform(
+        &self,
+        url: String,
+        token: String,
+        os: String,
+        arch: String,
+        variant: Option<String>,
+    ) -> Result<String, MirrorError>;
+
+    // streams a blob (layer) at `url` into `writer` chunk-by-chunk instead of
+    // buffering the whole body in memory, optionally reporting progress as
+    // (bytes downloaded so far, Content-Length total)
+    async fn download_blob(
+        &self,
+        url: String,
+        token: String,
+        writer: &mut (dyn AsyncWrite + Send + Unpin),
+        progress: Option<&(dyn Fn(u64, Option<u64>) + Send + Sync)>,
+    ) -> Result<(), MirrorError>;
 }
 #[async_trait]
 impl QueryImageInterface for ImplQueryImageInterface {
@@ -35,61 +207,259 @@ impl QueryImageInterface for ImplQueryImageInterface {
         e_tag: bool,
     ) -> Result<ResponseData, MirrorError> {
         let client = Client::new();
-        let mut header_map: HeaderMap = HeaderMap::new();
-        header_map.insert(USER_AGENT, HeaderValue::from_static("image-mirror"));
-        header_map.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
-        );
-        header_map.insert(
-            ACCEPT,
-            HeaderValue::from_static("application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json"),
-        );
-        header_map.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let header_map = Self::build_headers(&token);
         let get_url = if token.is_empty() {
             // check without token
             url.replace("https", "http")
         } else {
             url
         };
-        let res = client
-            .get(get_url)
-            .headers(header_map)
-            .send()
-            .await
-            .map_err(|e| MirrorError::new(&format!("[get_details] {e}")))?;
+
+        let res = self.get_with_retry(&client, &get_url, header_map).await?;
         if res.status() == StatusCode::OK {
-            let headers = res.headers();
-            if e_tag {
-                let e_tag = headers.get("docker-content-digest").unwrap();
-                Ok(ResponseData {
-                    data: e_tag.to_str().unwrap().to_string(),
-                    link: "".to_string(),
-                })
+            Self::parse_ok_response(res, e_tag).await
+        } else {
+            Err(MirrorError::new(&format!("[get_details] {}", res.status())))
+        }
+    }
+
+    async fn get_details_authenticated(
+        &self,
+        url: String,
+        credentials: Option<Credentials>,
+        e_tag: bool,
+    ) -> Result<ResponseData, MirrorError> {
+        let client = Client::new();
+        let res = self
+            .get_with_retry(&client, &url, Self::build_headers(""))
+            .await?;
+
+        if res.status() == StatusCode::OK {
+            return Self::parse_ok_response(res, e_tag).await;
+        }
+
+        if res.status() != StatusCode::UNAUTHORIZED {
+            return Err(MirrorError::new(&format!(
+                "[get_details_authenticated] {}",
+                res.status()
+            )));
+        }
+
+        let www_authenticate = res
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| {
+                MirrorError::new("[get_details_authenticated] missing WWW-Authenticate header")
+            })?
+            .to_string();
+        let challenge = auth::parse_challenge(&www_authenticate)?;
+
+        let cached = match &challenge.scope {
+            Some(scope) => self.token_cache.lock().unwrap().get(scope).cloned(),
+            None => None,
+        };
+        let used_cached = cached.is_some();
+        let token = match cached {
+            Some(token) => token,
+            None => auth::fetch_token(&client, &challenge, credentials.as_ref(), &self.retry_policy).await?,
+        };
+        if let Some(scope) = &challenge.scope {
+            self.token_cache
+                .lock()
+                .unwrap()
+                .insert(scope.clone(), token.clone());
+        }
+
+        let retried = self
+            .get_with_retry(&client, &url, Self::build_headers(&token))
+            .await?;
+
+        // a cached token can have expired server-side between requests; evict
+        // it and fetch a fresh one once before giving up
+        if retried.status() == StatusCode::UNAUTHORIZED && used_cached {
+            if let Some(scope) = &challenge.scope {
+                self.token_cache.lock().unwrap().remove(scope);
+            }
+            let token = auth::fetch_token(&client, &challenge, credentials.as_ref(), &self.retry_policy).await?;
+            if let Some(scope) = &challenge.scope {
+                self.token_cache
+                    .lock()
+                    .unwrap()
+                    .insert(scope.clone(), token.clone());
+            }
+
+            let retried = self
+                .get_with_retry(&client, &url, Self::build_headers(&token))
+                .await?;
+
+            return if retried.status() == StatusCode::OK {
+                Self::parse_ok_response(retried, e_tag).await
             } else {
-                let link_info = headers
-                    .get("link")
-                    .map(|l| {
-                        l.to_str()
-                            .unwrap()
-                            .replace(['<', '>'], "")
-                            .replace("; rel=\"next\"", "")
-                    })
-                    .unwrap_or_default();
-                let body = res.text().await.map_err(|e| {
-                    MirrorError::new(&format!(
-                        "[get_details] could not read body contents {}",
-                        e.to_string().to_lowercase()
-                    ))
-                })?;
-                Ok(ResponseData {
-                    data: body,
-                    link: link_info,
-                })
+                Err(MirrorError::new(&format!(
+                    "[get_details_authenticated] {}",
+                    retried.status()
+                )))
+            };
+        }
+
+        if retried.status() == StatusCode::OK {
+            Self::parse_ok_response(retried, e_tag).await
+        } else {
+            Err(MirrorError::new(&format!(
+                "[get_details_authenticated] {}",
+                retried.status()
+            )))
+        }
+    }
+
+    async fn get_all(&self, url: String, token: String) -> Result<ResponseData, MirrorError> {
+        let mut next_url = url.clone();
+        let mut merged: Option<serde_json::Value> = None;
+
+        loop {
+            let page = self.get_details(next_url.clone(), token.clone(), false).await?;
+            let page_body: serde_json::Value = serde_json::from_str(&page.data).map_err(|e| {
+                MirrorError::new(&format!("[get_all] could not parse page body {e}"))
+            })?;
+
+            merged = Some(match merged {
+                None => page_body,
+                Some(mut acc) => {
+                    merge_pages(&mut acc, &page_body);
+                    acc
+                }
+            });
+
+            if page.link.is_empty() {
+                break;
             }
+
+            let base = reqwest::Url::parse(&url)
+                .map_err(|e| MirrorError::new(&format!("[get_all] invalid url {e}")))?;
+            next_url = base
+                .join(&page.link)
+                .map_err(|e| MirrorError::new(&format!("[get_all] invalid link header {e}")))?
+                .to_string();
+        }
+
+        let data = serde_json::to_string(&merged.unwrap()).map_err(|e| {
+            MirrorError::new(&format!("[get_all] could not serialize merged body {e}"))
+        })?;
+        Ok(ResponseData {
+            data,
+            link: "".to_string(),
+        })
+    }
+
+    async fn get_verified(&self, url: String, token: String) -> Result<ResponseData, MirrorError> {
+        let client = Client::new();
+        let header_map = Self::build_headers(&token);
+        let get_url = if token.is_empty() {
+            url.replace("https", "http")
         } else {
-            Err(MirrorError::new(&format!("[get_details] {}", res.status())))
+            url.clone()
+        };
+        let res = self.get_with_retry(&client, &get_url, header_map).await?;
+        if res.status() != StatusCode::OK {
+            return Err(MirrorError::new(&format!(
+                "[get_verified] {}",
+                res.status()
+            )));
+        }
+
+        let expected = res
+            .headers()
+            .get("docker-content-digest")
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| {
+                MirrorError::new("[get_verified] missing docker-content-digest header")
+            })?
+            .to_string();
+
+        let bytes = res.bytes().await.map_err(|e| {
+            MirrorError::new(&format!(
+                "[get_verified] could not read body contents {}",
+                e.to_string().to_lowercase()
+            ))
+        })?;
+        let actual = digest::compute(&bytes);
+        digest::verify(&expected, &actual)?;
+        if let Some(pinned) = digest::pinned(&url) {
+            digest::verify(&pinned, &actual)?;
+        }
+
+        let data = String::from_utf8(bytes.to_vec()).map_err(|e| {
+            MirrorError::new(&format!("[get_verified] response body was not valid utf-8 {e}"))
+        })?;
+        Ok(ResponseData {
+            data,
+            link: "".to_string(),
+        })
+    }
+
+    async fn resolve_platform(
+        &self,
+        url: String,
+        token: String,
+        os: String,
+        arch: String,
+        variant: Option<String>,
+    ) -> Result<String, MirrorError> {
+        let res = self.get_details(url, token, false).await?;
+        let index = manifest::parse_index(&res.data)?;
+        manifest::find_platform(&index, &os, &arch, variant.as_deref())
+            .map(|m| m.digest.clone())
+            .ok_or_else(|| {
+                MirrorError::new(&format!(
+                    "[resolve_platform] no manifest found for {os}/{arch}{}",
+                    variant
+                        .as_deref()
+                        .map(|v| format!("/{v}"))
+                        .unwrap_or_default()
+                ))
+            })
+    }
+
+    async fn download_blob(
+        &self,
+        url: String,
+        token: String,
+        writer: &mut (dyn AsyncWrite + Send + Unpin),
+        progress: Option<&(dyn Fn(u64, Option<u64>) + Send + Sync)>,
+    ) -> Result<(), MirrorError> {
+        let client = Client::new();
+        let header_map = Self::build_headers(&token);
+        // unlike get_details/get_verified, blob urls are typically signed,
+        // short-lived CDN redirects from the registry; downgrading to plain
+        // HTTP for an anonymous pull can break hosts that require TLS, so the
+        // url is used as-is regardless of whether a token was supplied
+        let res = self.get_with_retry(&client, &url, header_map).await?;
+        if res.status() != StatusCode::OK {
+            return Err(MirrorError::new(&format!(
+                "[download_blob] {}",
+                res.status()
+            )));
         }
+
+        let total = res.content_length();
+        let mut downloaded: u64 = 0;
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| MirrorError::new(&format!("[download_blob] {e}")))?;
+            writer.write_all(&chunk).await.map_err(|e| {
+                MirrorError::new(&format!("[download_blob] could not write chunk {e}"))
+            })?;
+            downloaded += chunk.len() as u64;
+            if let Some(progress) = progress {
+                progress(downloaded, total);
+            }
+        }
+
+        writer.flush().await.map_err(|e| {
+            MirrorError::new(&format!("[download_blob] could not flush writer {e}"))
+        })?;
+        Ok(())
     }
 }
 
@@ -118,7 +488,7 @@ mod tests {
             .with_body("{ \"test\": \"hello-world\" }")
             .create();
 
-        let fake = ImplQueryImageInterface {};
+        let fake = ImplQueryImageInterface::new();
 
         let res = aw!(fake.get_details(url + "/v2/manifests", String::from("token"), false));
         assert!(res.is_ok());
@@ -140,9 +510,407 @@ mod tests {
             .with_header("Accept", "application/vnd.docker.distribution.manifest.list.v2+json,application/vnd.oci.image.index.v1+json,application/vnd.oci.image.manifest.v1+json")
             .create();
 
-        let fake = ImplQueryImageInterface {};
+        let fake = ImplQueryImageInterface::new();
 
         let res = aw!(fake.get_details(url + "/v2/manifests", String::from(""), false));
         assert!(res.is_err());
     }
+    #[test]
+    fn get_manifest_missing_digest_header_fail() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"test\": \"hello-world\" }")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_details(url + "/v2/manifests", String::from("token"), true));
+        assert!(res.is_err());
+    }
+    #[test]
+    fn get_details_authenticated_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(401)
+            .with_header(
+                "WWW-Authenticate",
+                &format!(
+                    "Bearer realm=\"{}/token\",service=\"registry\",scope=\"repository:foo:pull\"",
+                    url
+                ),
+            )
+            .create();
+        server
+            .mock("GET", "/token")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"token\": \"abc123\" }")
+            .create();
+        server
+            .mock("GET", "/v2/manifests")
+            .match_header("authorization", "Bearer abc123")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"test\": \"hello-world\" }")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_details_authenticated(url + "/v2/manifests", None, false));
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().data,
+            String::from("{ \"test\": \"hello-world\" }")
+        );
+    }
+    #[test]
+    fn get_details_authenticated_non_bearer_challenge_fail() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(401)
+            .with_header("WWW-Authenticate", "Basic realm=\"registry\"")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_details_authenticated(url + "/v2/manifests", None, false));
+        assert!(res.is_err());
+    }
+    #[test]
+    fn get_details_authenticated_stale_cached_token_refetches_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(401)
+            .with_header(
+                "WWW-Authenticate",
+                &format!(
+                    "Bearer realm=\"{}/token\",service=\"registry\",scope=\"repository:foo:pull\"",
+                    url
+                ),
+            )
+            .create();
+        server
+            .mock("GET", "/v2/manifests")
+            .match_header("authorization", "Bearer stale-token")
+            .with_status(401)
+            .create();
+        server
+            .mock("GET", "/token")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"token\": \"fresh-token\" }")
+            .create();
+        server
+            .mock("GET", "/v2/manifests")
+            .match_header("authorization", "Bearer fresh-token")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"test\": \"hello-world\" }")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+        fake.token_cache
+            .lock()
+            .unwrap()
+            .insert("repository:foo:pull".to_string(), "stale-token".to_string());
+
+        let res = aw!(fake.get_details_authenticated(url + "/v2/manifests", None, false));
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().data,
+            String::from("{ \"test\": \"hello-world\" }")
+        );
+        assert_eq!(
+            fake.token_cache.lock().unwrap().get("repository:foo:pull"),
+            Some(&"fresh-token".to_string())
+        );
+    }
+    #[test]
+    fn get_details_authenticated_stale_cached_token_refetch_also_rejected_fail() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(401)
+            .with_header(
+                "WWW-Authenticate",
+                &format!(
+                    "Bearer realm=\"{}/token\",service=\"registry\",scope=\"repository:foo:pull\"",
+                    url
+                ),
+            )
+            .create();
+        server
+            .mock("GET", "/v2/manifests")
+            .match_header("authorization", "Bearer stale-token")
+            .with_status(401)
+            .create();
+        server
+            .mock("GET", "/token")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"token\": \"still-bad-token\" }")
+            .create();
+        server
+            .mock("GET", "/v2/manifests")
+            .match_header("authorization", "Bearer still-bad-token")
+            .with_status(401)
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+        fake.token_cache
+            .lock()
+            .unwrap()
+            .insert("repository:foo:pull".to_string(), "stale-token".to_string());
+
+        let res = aw!(fake.get_details_authenticated(url + "/v2/manifests", None, false));
+        assert!(res.is_err());
+    }
+    #[test]
+    fn get_all_follows_pagination_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/_catalog")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_header("link", "</v2/_catalog?last=foo&n=1>; rel=\"next\"")
+            .with_body("{ \"repositories\": [\"foo\"] }")
+            .create();
+        server
+            .mock("GET", "/v2/_catalog?last=foo&n=1")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"repositories\": [\"bar\"] }")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_all(url + "/v2/_catalog", String::from("token")));
+        assert!(res.is_ok());
+        let data: serde_json::Value = serde_json::from_str(&res.unwrap().data).unwrap();
+        assert_eq!(data["repositories"], serde_json::json!(["foo", "bar"]));
+    }
+    #[test]
+    fn get_verified_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests/sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_header(
+                "docker-content-digest",
+                "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            )
+            .with_body("")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_verified(
+            url + "/v2/manifests/sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            String::from("token")
+        ));
+        assert!(res.is_ok());
+    }
+    #[test]
+    fn get_verified_mismatch_fail() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_header("docker-content-digest", "sha256:deadbeef")
+            .with_body("{ \"test\": \"hello-world\" }")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_verified(url + "/v2/manifests", String::from("token")));
+        assert!(res.is_err());
+    }
+    #[test]
+    fn resolve_platform_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                r#"{ "manifests": [
+                    { "digest": "sha256:amd64", "mediaType": "application/vnd.oci.image.manifest.v1+json", "platform": { "os": "linux", "architecture": "amd64" } },
+                    { "digest": "sha256:arm64", "mediaType": "application/vnd.oci.image.manifest.v1+json", "platform": { "os": "linux", "architecture": "arm64" } }
+                ] }"#,
+            )
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.resolve_platform(
+            url + "/v2/manifests",
+            String::from("token"),
+            String::from("linux"),
+            String::from("arm64"),
+            None
+        ));
+        assert_eq!(res.unwrap(), "sha256:arm64");
+    }
+    #[test]
+    fn download_blob_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/blobs/sha256:abc123")
+            .with_status(200)
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body("binary-layer-contents")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let mut buf: Vec<u8> = Vec::new();
+        let last_progress: std::sync::Mutex<Option<(u64, Option<u64>)>> =
+            std::sync::Mutex::new(None);
+        let report = |downloaded: u64, total: Option<u64>| {
+            *last_progress.lock().unwrap() = Some((downloaded, total));
+        };
+        let res = aw!(fake.download_blob(
+            url + "/v2/blobs/sha256:abc123",
+            String::from("token"),
+            &mut buf,
+            Some(&report),
+        ));
+        assert!(res.is_ok());
+        assert_eq!(buf, b"binary-layer-contents".to_vec());
+        assert_eq!(*last_progress.lock().unwrap(), Some((21, Some(21))));
+    }
+    #[test]
+    fn download_blob_empty_token_does_not_mangle_url_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        // a path containing "https" as plain text; if download_blob still
+        // carried get_details's https->http downgrade, this would be mangled
+        // to "/v2/blobs/https-mirror/sha256:abc123" -> "...http-mirror..."
+        // and fail to match the mock below
+        server
+            .mock("GET", "/v2/blobs/https-mirror/sha256:abc123")
+            .with_status(200)
+            .with_header("Content-Type", "application/octet-stream")
+            .with_body("binary-layer-contents")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let mut buf: Vec<u8> = Vec::new();
+        let res = aw!(fake.download_blob(
+            url + "/v2/blobs/https-mirror/sha256:abc123",
+            String::new(),
+            &mut buf,
+            None,
+        ));
+        assert!(res.is_ok());
+        assert_eq!(buf, b"binary-layer-contents".to_vec());
+    }
+    #[test]
+    fn get_details_retries_after_429_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(429)
+            .with_header("Retry-After", "0")
+            .expect(1)
+            .create();
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body("{ \"test\": \"hello-world\" }")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let res = aw!(fake.get_details(url + "/v2/manifests", String::from("token"), false));
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap().data,
+            String::from("{ \"test\": \"hello-world\" }")
+        );
+    }
+    #[test]
+    fn get_details_gives_up_after_max_retries_fail() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/manifests")
+            .with_status(503)
+            .create();
+
+        let fake = ImplQueryImageInterface::with_retry_policy(RetryPolicy {
+            max_retries: 1,
+            base_delay: std::time::Duration::from_millis(1),
+            retry_on_5xx: true,
+        });
+
+        let res = aw!(fake.get_details(url + "/v2/manifests", String::from("token"), false));
+        assert!(res.is_err());
+    }
+    #[test]
+    fn download_blob_retries_after_429_pass() {
+        let mut server = mockito::Server::new();
+        let url = server.url();
+
+        server
+            .mock("GET", "/v2/blobs/sha256:abc123")
+            .with_status(429)
+            .with_header("Retry-After", "0")
+            .expect(1)
+            .create();
+        server
+            .mock("GET", "/v2/blobs/sha256:abc123")
+            .with_status(200)
+            .with_body("binary-layer-contents")
+            .create();
+
+        let fake = ImplQueryImageInterface::new();
+
+        let mut buf: Vec<u8> = Vec::new();
+        let res = aw!(fake.download_blob(
+            url + "/v2/blobs/sha256:abc123",
+            String::from("token"),
+            &mut buf,
+            None,
+        ));
+        assert!(res.is_ok());
+        assert_eq!(buf, b"binary-layer-contents".to_vec());
+    }
 }
@@ -0,0 +1,93 @@
+use mirror_error::MirrorError;
+use serde::Deserialize;
+
+// target platform of a single child manifest in a manifest-list / image index
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+// one child entry of a docker manifest-list or OCI image index
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ManifestDescriptor {
+    pub digest: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub platform: Platform,
+}
+
+// a parsed `application/vnd.docker.distribution.manifest.list.v2+json` or
+// `application/vnd.oci.image.index.v1+json` response
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ManifestList {
+    pub manifests: Vec<ManifestDescriptor>,
+}
+
+pub(crate) fn parse_index(body: &str) -> Result<ManifestList, MirrorError> {
+    serde_json::from_str(body)
+        .map_err(|e| MirrorError::new(&format!("[parse_index] could not parse manifest index {e}")))
+}
+
+// find the child manifest matching (os, arch, variant); the architecture-only
+// fallback only applies when the caller didn't request a variant at all - an
+// explicit variant that isn't found must not silently resolve to a different
+// one
+pub(crate) fn find_platform<'a>(
+    list: &'a ManifestList,
+    os: &str,
+    arch: &str,
+    variant: Option<&str>,
+) -> Option<&'a ManifestDescriptor> {
+    match variant {
+        Some(variant) => list.manifests.iter().find(|m| {
+            m.platform.os == os
+                && m.platform.architecture == arch
+                && m.platform.variant.as_deref() == Some(variant)
+        }),
+        None => list
+            .manifests
+            .iter()
+            .find(|m| m.platform.os == os && m.platform.architecture == arch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"{
+        "manifests": [
+            { "digest": "sha256:amd64", "mediaType": "application/vnd.oci.image.manifest.v1+json", "platform": { "os": "linux", "architecture": "amd64" } },
+            { "digest": "sha256:arm64", "mediaType": "application/vnd.oci.image.manifest.v1+json", "platform": { "os": "linux", "architecture": "arm", "variant": "v7" } }
+        ]
+    }"#;
+
+    #[test]
+    fn find_platform_exact_pass() {
+        let list = parse_index(INDEX).unwrap();
+        let found = find_platform(&list, "linux", "amd64", None).unwrap();
+        assert_eq!(found.digest, "sha256:amd64");
+    }
+
+    #[test]
+    fn find_platform_variant_fallback_pass() {
+        let list = parse_index(INDEX).unwrap();
+        let found = find_platform(&list, "linux", "arm", None).unwrap();
+        assert_eq!(found.digest, "sha256:arm64");
+    }
+
+    #[test]
+    fn find_platform_missing_pass() {
+        let list = parse_index(INDEX).unwrap();
+        assert!(find_platform(&list, "windows", "amd64", None).is_none());
+    }
+
+    #[test]
+    fn find_platform_variant_mismatch_fail() {
+        let list = parse_index(INDEX).unwrap();
+        assert!(find_platform(&list, "linux", "arm", Some("v6")).is_none());
+    }
+}
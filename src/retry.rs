@@ -0,0 +1,89 @@
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::time::Duration;
+
+// retry behaviour for transient registry errors (429 / 5xx)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub retry_on_5xx: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            retry_on_5xx: true,
+        }
+    }
+}
+
+pub(crate) fn should_retry(policy: &RetryPolicy, status: StatusCode, attempt: u32) -> bool {
+    if attempt >= policy.max_retries {
+        return false;
+    }
+    status == StatusCode::TOO_MANY_REQUESTS || (policy.retry_on_5xx && status.is_server_error())
+}
+
+// how long to wait before the next attempt: honor `Retry-After` when the
+// registry sent one, otherwise fall back to exponential backoff with jitter
+pub(crate) fn wait_duration(policy: &RetryPolicy, res: &Response, attempt: u32) -> Duration {
+    res.headers()
+        .get(RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(parse_retry_after)
+        .unwrap_or_else(|| backoff(policy.base_delay, attempt))
+}
+
+// a `Retry-After` value is either a number of seconds or an HTTP-date
+fn parse_retry_after(header: &str) -> Option<Duration> {
+    let header = header.trim();
+    if let Ok(secs) = header.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let when = httpdate::parse_http_date(header).ok()?;
+    when.duration_since(std::time::SystemTime::now()).ok()
+}
+
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let exp = base.saturating_mul(1 << attempt.min(16));
+    let jitter_ms = rand::thread_rng().gen_range(0..=exp.as_millis().max(1) as u64 / 4 + 1);
+    exp + Duration::from_millis(jitter_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_retry_429_pass() {
+        let policy = RetryPolicy::default();
+        assert!(should_retry(&policy, StatusCode::TOO_MANY_REQUESTS, 0));
+    }
+
+    #[test]
+    fn should_retry_exhausted_fail() {
+        let policy = RetryPolicy {
+            max_retries: 1,
+            ..RetryPolicy::default()
+        };
+        assert!(!should_retry(&policy, StatusCode::TOO_MANY_REQUESTS, 1));
+    }
+
+    #[test]
+    fn should_retry_5xx_disabled_fail() {
+        let policy = RetryPolicy {
+            retry_on_5xx: false,
+            ..RetryPolicy::default()
+        };
+        assert!(!should_retry(&policy, StatusCode::SERVICE_UNAVAILABLE, 0));
+    }
+
+    #[test]
+    fn parse_retry_after_seconds_pass() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+    }
+}